@@ -0,0 +1,72 @@
+//! Source image data shared by all backends.
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+};
+
+use image::{DynamicImage, GenericImageView, ImageFormat};
+use ratatui::layout::Rect;
+
+use crate::FontSize;
+
+/// A decoded image, and the metadata backends need to resize, cache and render it.
+#[derive(Clone)]
+pub struct ImageSource {
+    pub image: DynamicImage,
+    pub area: Rect,
+    pub hash: u64,
+    encoded: Option<(Vec<u8>, ImageFormat)>,
+}
+
+impl ImageSource {
+    /// Build a source from an already-decoded image.
+    pub fn new(image: DynamicImage, font_size: FontSize) -> Self {
+        Self::build(image, font_size, None)
+    }
+
+    /// Build a source that also retains the bytes `image` was originally encoded with, so
+    /// backends that support it (currently just iTerm2) can pass them through unchanged
+    /// instead of re-encoding to PNG.
+    pub fn new_with_encoded(
+        image: DynamicImage,
+        encoded: Vec<u8>,
+        format: ImageFormat,
+        font_size: FontSize,
+    ) -> Self {
+        Self::build(image, font_size, Some((encoded, format)))
+    }
+
+    fn build(
+        image: DynamicImage,
+        font_size: FontSize,
+        encoded: Option<(Vec<u8>, ImageFormat)>,
+    ) -> Self {
+        let (width, height) = image.dimensions();
+        let (font_width, font_height) = font_size;
+        let area = Rect::new(
+            0,
+            0,
+            (width / u32::from(font_width)) as u16,
+            (height / u32::from(font_height)) as u16,
+        );
+
+        let mut hasher = DefaultHasher::new();
+        image.as_bytes().hash(&mut hasher);
+        let hash = hasher.finish();
+
+        Self {
+            image,
+            area,
+            hash,
+            encoded,
+        }
+    }
+
+    /// The bytes `image` was originally encoded with, and their format, if this source
+    /// retained them.
+    pub fn original(&self) -> Option<(&[u8], ImageFormat)> {
+        self.encoded
+            .as_ref()
+            .map(|(bytes, format)| (bytes.as_slice(), *format))
+    }
+}
@@ -6,6 +6,11 @@ use ratatui::layout::Rect;
 use rustix::termios::Winsize;
 #[cfg(feature = "serde")]
 use serde::Deserialize;
+#[cfg(feature = "rustix")]
+use std::{
+    io::{Read, Write},
+    time::{Duration, Instant},
+};
 
 #[cfg(feature = "sixel")]
 use crate::backend::sixel::{resizeable::SixelState, FixedSixel};
@@ -13,6 +18,7 @@ use crate::backend::sixel::{resizeable::SixelState, FixedSixel};
 use crate::{
     backend::{
         halfblocks::{resizeable::HalfblocksState, FixedHalfblocks},
+        iterm2::{FixedIterm2, Iterm2State},
         kitty::{FixedKitty, KittyState},
         FixedBackend, ResizeBackend,
     },
@@ -25,6 +31,7 @@ pub struct Picker {
     background_color: Option<Rgb<u8>>,
     backend_type: BackendType,
     kitty_counter: u8,
+    is_tmux: bool,
 }
 
 #[derive(PartialEq, Clone, Debug, Copy)]
@@ -38,6 +45,7 @@ pub enum BackendType {
     #[cfg(feature = "sixel")]
     Sixel,
     Kitty,
+    Iterm2,
 }
 
 impl BackendType {
@@ -49,7 +57,8 @@ impl BackendType {
             BackendType::Halfblocks => BackendType::Sixel,
             #[cfg(feature = "sixel")]
             BackendType::Sixel => BackendType::Kitty,
-            BackendType::Kitty => BackendType::Halfblocks,
+            BackendType::Kitty => BackendType::Iterm2,
+            BackendType::Iterm2 => BackendType::Halfblocks,
         }
     }
 }
@@ -97,6 +106,7 @@ impl Picker {
             background_color,
             backend_type,
             kitty_counter: 0,
+            is_tmux: is_tmux(),
         })
     }
 
@@ -111,11 +121,33 @@ impl Picker {
         Picker::new(font_size, backend_type, background_color)
     }
 
+    // Escape-sequence alternative to `from_ioctl`'s `TIOCGWINSZ`, for multiplexers where it
+    // reports zero pixel dimensions. Like `from_ioctl`, it needs the `rustix` feature, since it
+    // uses rustix's termios to put stdin into raw mode; it is not available without it.
+    /// Query the terminal for font size via escape sequences.
+    #[cfg(feature = "rustix")]
+    pub fn from_query(
+        backend_type: BackendType,
+        background_color: Option<Rgb<u8>>,
+    ) -> Result<Picker> {
+        let font_size = font_size_from_query()?;
+        Picker::new(font_size, backend_type, background_color)
+    }
+
     pub fn guess(&mut self) -> BackendType {
         self.backend_type = guess_backend();
         self.backend_type
     }
 
+    // More reliable than `guess()` under multiplexers or over SSH, at the cost of briefly
+    // putting stdin into raw mode. Falls back to `guess()` on any I/O error.
+    /// Guess the backend by querying the terminal's graphics capabilities.
+    #[cfg(feature = "rustix")]
+    pub fn guess_from_query(&mut self) -> BackendType {
+        self.backend_type = query_backend().unwrap_or_else(|_| guess_backend());
+        self.backend_type
+    }
+
     /// Set a specific backend
     pub fn set(&mut self, r#type: BackendType) {
         self.backend_type = r#type;
@@ -135,6 +167,31 @@ impl Picker {
         resize: Resize,
     ) -> Result<Box<dyn FixedBackend>> {
         let source = ImageSource::new(image, self.font_size);
+        self.new_static_fit_from_source(source, size, resize)
+    }
+
+    /// Like [`Picker::new_static_fit`], but also retains the bytes `image` was originally
+    /// encoded with (e.g. the raw file contents) and their [`image::ImageFormat`], so backends
+    /// that support it (currently just iTerm2) can pass them through unchanged instead of
+    /// re-encoding to PNG.
+    pub fn new_static_fit_with_encoded(
+        &mut self,
+        image: DynamicImage,
+        encoded: Vec<u8>,
+        format: image::ImageFormat,
+        size: Rect,
+        resize: Resize,
+    ) -> Result<Box<dyn FixedBackend>> {
+        let source = ImageSource::new_with_encoded(image, encoded, format, self.font_size);
+        self.new_static_fit_from_source(source, size, resize)
+    }
+
+    fn new_static_fit_from_source(
+        &mut self,
+        source: ImageSource,
+        size: Rect,
+        resize: Resize,
+    ) -> Result<Box<dyn FixedBackend>> {
         match self.backend_type {
             BackendType::Halfblocks => Ok(Box::new(FixedHalfblocks::from_source(
                 &source,
@@ -159,6 +216,13 @@ impl Picker {
                     self.kitty_counter,
                 )?))
             }
+            BackendType::Iterm2 => Ok(Box::new(FixedIterm2::from_source(
+                &source,
+                resize,
+                self.background_color,
+                size,
+                self.is_tmux,
+            )?)),
         }
     }
 
@@ -172,6 +236,7 @@ impl Picker {
                 self.kitty_counter += 1;
                 Box::new(KittyState::new(self.kitty_counter))
             }
+            BackendType::Iterm2 => Box::new(Iterm2State::new(self.is_tmux)),
         }
     }
 
@@ -198,16 +263,59 @@ pub fn font_size(winsize: Winsize) -> Result<FontSize> {
     Ok((x / cols, y / rows))
 }
 
+// Query the text area's cell size directly (xterm "report cell size in pixels"), falling back to
+// the window pixel size divided by the character grid size when the terminal doesn't support it.
+// see https://invisible-island.net/xterm/ctlseqs/ctlseqs.html#h2-Window-manipulation
+#[cfg(feature = "rustix")]
+fn font_size_from_query() -> Result<FontSize> {
+    if let Ok(response) = query_terminal(b"\x1b[16t", b't', QUERY_TIMEOUT) {
+        if let Some((6, height, width)) = parse_csi_reply(&response) {
+            if height != 0 && width != 0 {
+                return Ok((width, height));
+            }
+        }
+    }
+
+    let pixels = query_terminal(b"\x1b[14t", b't', QUERY_TIMEOUT)?;
+    let (_, ypix, xpix) =
+        parse_csi_reply(&pixels).ok_or_else(|| String::from("font_size zero value"))?;
+
+    let cells = query_terminal(b"\x1b[18t", b't', QUERY_TIMEOUT)?;
+    let (_, rows, cols) =
+        parse_csi_reply(&cells).ok_or_else(|| String::from("font_size zero value"))?;
+
+    if xpix == 0 || ypix == 0 || cols == 0 || rows == 0 {
+        return Err(String::from("font_size zero value").into());
+    }
+    Ok((xpix / cols, ypix / rows))
+}
+
+// Parses a `\x1b[<kind>;<a>;<b>t` window-manipulation reply into its three numeric fields.
+#[cfg(feature = "rustix")]
+fn parse_csi_reply(response: &[u8]) -> Option<(u16, u16, u16)> {
+    let text = std::str::from_utf8(response).ok()?;
+    let body = text.rsplit("\x1b[").next()?.trim_end_matches('t');
+    let mut parts = body.split(';');
+    let kind = parts.next()?.parse().ok()?;
+    let a = parts.next()?.parse().ok()?;
+    let b = parts.next()?.parse().ok()?;
+    Some((kind, a, b))
+}
+
 // Check if Sixel protocol can be used
 fn guess_backend() -> BackendType {
+    if is_iterm2() {
+        return BackendType::Iterm2;
+    }
     if let Ok(term) = std::env::var("TERM") {
         match term.as_str() {
             "mlterm" | "yaft-256color" | "foot" | "foot-extra" | "alacritty" => {
                 return BackendType::Sixel
             }
-            "st-256color" | "xterm" | "xterm-256color" => {
-                return check_device_attrs().unwrap_or(BackendType::Halfblocks)
-            }
+            // xterm and friends report Sixel support only via a device-attributes query,
+            // which needs I/O. Keep this fallback I/O-free and use `guess_from_query()` for
+            // real capability probing.
+            "st-256color" | "xterm" | "xterm-256color" => return BackendType::Halfblocks,
             term => {
                 if term.contains("kitty") {
                     return BackendType::Kitty;
@@ -223,28 +331,114 @@ fn guess_backend() -> BackendType {
     BackendType::Halfblocks
 }
 
-// Check if Sixel is within the terminal's attributes
-// see https://invisible-island.net/xterm/ctlseqs/ctlseqs.html#h2-Sixel-Graphics
-// and https://vt100.net/docs/vt510-rm/DA1.html
-fn check_device_attrs() -> Result<BackendType> {
-    todo!();
-    // let mut term = Term::stdout();
-    //
-    // write!(&mut term, "\x1b[c")?;
-    // term.flush()?;
-    //
-    // let mut response = String::new();
-    //
-    // while let Ok(key) = term.read_key() {
-    // if let Key::Char(c) = key {
-    // response.push(c);
-    // if c == 'c' {
-    // break;
-    // }
-    // }
-    // }
-    //
-    // Ok(response.contains(";4;") || response.contains(";4c"))
+// Check if the terminal identifies itself as iTerm2, or another terminal that implements its
+// inline image protocol (e.g. WezTerm).
+fn is_iterm2() -> bool {
+    if let Ok(term_program) = std::env::var("TERM_PROGRAM") {
+        if term_program == "iTerm.app" || term_program == "WezTerm" {
+            return true;
+        }
+    }
+    if let Ok(lc_terminal) = std::env::var("LC_TERMINAL") {
+        if lc_terminal == "iTerm2" {
+            return true;
+        }
+    }
+    false
+}
+
+// Check if we're running inside tmux, which requires wrapping escape sequences in a tmux
+// passthrough (see `Iterm2::from_source`).
+fn is_tmux() -> bool {
+    std::env::var("TERM")
+        .map(|term| term.starts_with("screen") || term.starts_with("tmux"))
+        .unwrap_or(false)
+}
+
+/// Query the terminal's graphics capabilities via escape sequences, preferring
+/// Kitty > iTerm2 > Sixel > Halfblocks.
+///
+/// The Kitty graphics query and the DA1 query are written together and the reply drained in a
+/// single pass: terminals that don't understand the Kitty query simply ignore it, so the DA1
+/// reply still arrives and acts as a sentinel marking the end of the response.
+#[cfg(feature = "rustix")]
+fn query_backend() -> Result<BackendType> {
+    let mut query = Vec::with_capacity(KITTY_QUERY.len() + DA1_QUERY.len());
+    query.extend_from_slice(KITTY_QUERY);
+    query.extend_from_slice(DA1_QUERY);
+    let response = query_terminal(&query, b'c', QUERY_TIMEOUT)?;
+
+    if response.windows(KITTY_OK.len()).any(|w| w == KITTY_OK) {
+        return Ok(BackendType::Kitty);
+    }
+    if is_iterm2() {
+        return Ok(BackendType::Iterm2);
+    }
+    if da1_params(&response).any(|param| param == "4") {
+        return Ok(BackendType::Sixel);
+    }
+    Ok(BackendType::Halfblocks)
+}
+
+#[cfg(feature = "rustix")]
+const QUERY_TIMEOUT: Duration = Duration::from_millis(100);
+
+// Primary Device Attributes request, see https://vt100.net/docs/vt510-rm/DA1.html
+#[cfg(feature = "rustix")]
+const DA1_QUERY: &[u8] = b"\x1b[c";
+
+// Kitty graphics protocol query action, see https://sw.kovidgoyal.net/kitty/graphics-protocol/#querying-support
+#[cfg(feature = "rustix")]
+const KITTY_QUERY: &[u8] = b"\x1b_Gi=31,s=1,v=1,a=q,t=d,f=24;AAAA\x1b\\";
+#[cfg(feature = "rustix")]
+const KITTY_OK: &[u8] = b"\x1b_Gi=31;OK";
+
+// Splits a DA1 reply of the form "\x1b[?<param>;<param>;...c" into its semicolon-separated
+// parameters.
+#[cfg(feature = "rustix")]
+fn da1_params(response: &[u8]) -> impl Iterator<Item = &str> {
+    let text = std::str::from_utf8(response).unwrap_or("");
+    let body = text
+        .rsplit_once("\x1b[")
+        .map_or("", |(_, body)| body)
+        .trim_start_matches('?')
+        .trim_end_matches('c');
+    body.split(';').filter(|param| !param.is_empty())
+}
+
+/// Write `query` and read the terminal's reply, with stdin in raw mode, until `terminator` is
+/// seen as the last byte of the response or `timeout` elapses.
+#[cfg(feature = "rustix")]
+fn query_terminal(query: &[u8], terminator: u8, timeout: Duration) -> Result<Vec<u8>> {
+    use rustix::termios::{LocalModes, OptionalActions};
+
+    let stdin = rustix::stdio::stdin();
+    let mut termios = rustix::termios::tcgetattr(stdin)?;
+    let original = termios.clone();
+    termios.local_modes &= !(LocalModes::ICANON | LocalModes::ECHO);
+    termios.special_codes[rustix::termios::SpecialCodeIndex::VMIN] = 0;
+    termios.special_codes[rustix::termios::SpecialCodeIndex::VTIME] = 1;
+    rustix::termios::tcsetattr(stdin, OptionalActions::Now, &termios)?;
+
+    let result = (|| -> Result<Vec<u8>> {
+        std::io::stdout().write_all(query)?;
+        std::io::stdout().flush()?;
+
+        let mut response = Vec::new();
+        let mut buf = [0u8; 256];
+        let start = Instant::now();
+        while start.elapsed() < timeout {
+            let read = std::io::stdin().read(&mut buf)?;
+            response.extend_from_slice(&buf[..read]);
+            if response.last() == Some(&terminator) {
+                break;
+            }
+        }
+        Ok(response)
+    })();
+
+    rustix::termios::tcsetattr(stdin, OptionalActions::Now, &original)?;
+    result
 }
 
 #[cfg(all(test, feature = "rustix", feature = "sixel"))]
@@ -278,6 +472,50 @@ mod tests {
         #[cfg(feature = "sixel")]
         assert_eq!(picker.cycle_backends(), BackendType::Sixel);
         assert_eq!(picker.cycle_backends(), BackendType::Kitty);
+        assert_eq!(picker.cycle_backends(), BackendType::Iterm2);
         assert_eq!(picker.cycle_backends(), BackendType::Halfblocks);
     }
 }
+
+#[cfg(all(test, feature = "rustix"))]
+mod query_tests {
+    use crate::picker::{da1_params, parse_csi_reply};
+
+    #[test]
+    fn da1_params_splits_on_semicolons() {
+        let params: Vec<_> = da1_params(b"\x1b[?62;1;2;4;6c").collect();
+        assert_eq!(params, vec!["62", "1", "2", "4", "6"]);
+    }
+
+    #[test]
+    fn da1_params_detects_sixel_support() {
+        assert!(da1_params(b"\x1b[?1;2;4c").any(|param| param == "4"));
+        assert!(!da1_params(b"\x1b[?1;2;6c").any(|param| param == "4"));
+    }
+
+    #[test]
+    fn da1_params_ignores_input_without_a_csi_prefix() {
+        assert!(!da1_params(b"garbage, no csi or terminator").any(|param| param == "4"));
+        assert_eq!(da1_params(b"\x1b[").count(), 0);
+    }
+
+    #[test]
+    fn parse_csi_reply_parses_the_three_fields() {
+        assert_eq!(parse_csi_reply(b"\x1b[6;20;10t"), Some((6, 20, 10)));
+    }
+
+    #[test]
+    fn parse_csi_reply_ignores_leading_noise() {
+        assert_eq!(
+            parse_csi_reply(b"noise\x1b[4;600;800t"),
+            Some((4, 600, 800))
+        );
+    }
+
+    #[test]
+    fn parse_csi_reply_rejects_malformed_input() {
+        assert_eq!(parse_csi_reply(b"not a csi reply"), None);
+        assert_eq!(parse_csi_reply(b"\x1b[6;20t"), None);
+        assert_eq!(parse_csi_reply(b"\x1b[6;abc;10t"), None);
+    }
+}
@@ -1,6 +1,6 @@
 //! ITerm2 protocol implementation.
 use base64::{engine::general_purpose, Engine};
-use image::{DynamicImage, Rgba};
+use image::{DynamicImage, ImageFormat, Rgba};
 use ratatui::{buffer::Buffer, layout::Rect};
 use std::{cmp::min, format, io::Cursor};
 
@@ -33,12 +33,12 @@ impl Iterm2 {
             background_color,
             false,
         );
-        let (image, area) = match resized {
-            Some((ref image, desired)) => (image, desired),
-            None => (&source.image, source.area),
+        let (image, area, original) = match resized {
+            Some((ref image, desired)) => (image, desired, None),
+            None => (&source.image, source.area, source.original()),
         };
 
-        let data = encode(image, is_tmux)?;
+        let data = encode(image, original, is_tmux)?;
         Ok(Self {
             data,
             area,
@@ -47,12 +47,29 @@ impl Iterm2 {
     }
 }
 
-// TODO: change E to sixel_rs::status::Error and map when calling
-fn encode(img: &DynamicImage, is_tmux: bool) -> Result<String> {
-    let mut png: Vec<u8> = vec![];
-    img.write_to(&mut Cursor::new(&mut png), image::ImageFormat::Png)?;
+// Original, still-encoded bytes and their format, passed through verbatim instead of
+// re-encoding to PNG. See [`ImageSource::original`].
+type OriginalBytes<'a> = (&'a [u8], ImageFormat);
 
-    let data = general_purpose::STANDARD.encode(&png);
+// TODO: change E to sixel_rs::status::Error and map when calling
+fn encode(img: &DynamicImage, original: Option<OriginalBytes>, is_tmux: bool) -> Result<String> {
+    let (data, len, name) = match original {
+        // No resize or background fill was needed: ship the source bytes as-is. This is both
+        // faster for already-compressed sources (no PNG re-encode) and preserves animation for
+        // formats like GIF, since iTerm2's inline-image protocol decodes whatever format the
+        // terminal itself understands.
+        Some((bytes, format)) => (
+            general_purpose::STANDARD.encode(bytes),
+            bytes.len(),
+            file_name(format),
+        ),
+        None => {
+            let mut png: Vec<u8> = vec![];
+            img.write_to(&mut Cursor::new(&mut png), ImageFormat::Png)?;
+            (general_purpose::STANDARD.encode(&png), png.len(), "image.png")
+        }
+    };
+    let name = general_purpose::STANDARD.encode(name);
 
     let (start, end) = if is_tmux {
         ("\x1bPtmux;\x1b\x1b", "\x1b\\")
@@ -60,14 +77,25 @@ fn encode(img: &DynamicImage, is_tmux: bool) -> Result<String> {
         ("\x1b", "")
     };
     Ok(format!(
-        "{start}]1337;File=inline=1;size={};width={}px;height={}px;doNotMoveCursor=1:{}\x07{end}",
-        png.len(),
+        "{start}]1337;File=name={name};inline=1;size={len};width={}px;height={}px;doNotMoveCursor=1:{data}\x07{end}",
         img.width(),
         img.height(),
-        data,
     ))
 }
 
+// A file name iTerm2 can use to infer the image's type, for the protocol's `name=` field.
+fn file_name(format: ImageFormat) -> &'static str {
+    match format {
+        ImageFormat::Png => "image.png",
+        ImageFormat::Jpeg => "image.jpg",
+        ImageFormat::Gif => "image.gif",
+        ImageFormat::WebP => "image.webp",
+        ImageFormat::Bmp => "image.bmp",
+        ImageFormat::Tiff => "image.tiff",
+        _ => "image",
+    }
+}
+
 impl ProtocolTrait for Iterm2 {
     fn render(&mut self, area: Rect, buf: &mut Buffer) {
         render(self.area, &self.data, area, buf, false)
@@ -159,7 +187,13 @@ impl StatefulProtocolTrait for StatefulIterm2 {
             force,
         ) {
             let is_tmux = self.current.is_tmux;
-            match encode(&img, is_tmux) {
+            // Only pass the original bytes through when the resized image is still the
+            // untouched source at its natural size; any real resize or background fill falls
+            // back to the PNG re-encode above.
+            let original = (rect == self.source.area && background_color.is_none())
+                .then(|| self.source.original())
+                .flatten();
+            match encode(&img, original, is_tmux) {
                 Ok(data) => {
                     self.current = Iterm2 {
                         data,
@@ -178,3 +212,59 @@ impl StatefulProtocolTrait for StatefulIterm2 {
         render(self.current.area, &self.current.data, area, buf, true);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tiny_image() -> DynamicImage {
+        DynamicImage::new_rgb8(2, 2)
+    }
+
+    #[test]
+    fn file_name_maps_known_formats() {
+        assert_eq!(file_name(ImageFormat::Png), "image.png");
+        assert_eq!(file_name(ImageFormat::Jpeg), "image.jpg");
+        assert_eq!(file_name(ImageFormat::Gif), "image.gif");
+        assert_eq!(file_name(ImageFormat::WebP), "image.webp");
+        assert_eq!(file_name(ImageFormat::Bmp), "image.bmp");
+        assert_eq!(file_name(ImageFormat::Tiff), "image.tiff");
+    }
+
+    #[test]
+    fn file_name_falls_back_for_unmapped_formats() {
+        assert_eq!(file_name(ImageFormat::Ico), "image");
+    }
+
+    #[test]
+    fn encode_passes_through_original_bytes() {
+        let img = tiny_image();
+        let bytes = b"not actually a gif, just test bytes".to_vec();
+
+        let data = encode(&img, Some((&bytes, ImageFormat::Gif)), false).unwrap();
+
+        assert!(data.contains("name=aW1hZ2UuZ2lm")); // base64("image.gif")
+        assert!(data.contains(&format!("size={}", bytes.len())));
+        assert!(data.contains(&general_purpose::STANDARD.encode(&bytes)));
+    }
+
+    #[test]
+    fn encode_falls_back_to_png_without_original_bytes() {
+        let img = tiny_image();
+
+        let data = encode(&img, None, false).unwrap();
+
+        assert!(data.contains("name=aW1hZ2UucG5n")); // base64("image.png")
+        assert!(!data.contains("size=0"));
+    }
+
+    #[test]
+    fn encode_wraps_payload_for_tmux() {
+        let img = tiny_image();
+
+        let data = encode(&img, None, true).unwrap();
+
+        assert!(data.starts_with("\x1bPtmux;\x1b\x1b]1337;"));
+        assert!(data.ends_with("\x1b\\"));
+    }
+}